@@ -0,0 +1,28 @@
+pub struct ComputePipeline {
+    pub pipeline: wgpu::ComputePipeline,
+}
+
+impl ComputePipeline {
+    pub fn new(
+        device: &wgpu::Device,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        shader: &wgpu::ShaderModule,
+        entry_point: &str,
+        label: &str,
+    ) -> Self {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&format!("{} Layout", label)),
+            bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(label),
+            layout: Some(&layout),
+            module: shader,
+            entry_point,
+        });
+
+        Self { pipeline }
+    }
+}
@@ -7,74 +7,57 @@ use winit::{
 use log::{debug, info, error};
 use winit::window::Window;
 
-#[macro_use]
-extern crate bitflags;
-
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct Vertex {
-    position: [f32; 3],
-    tex_coords: [f32; 2],
+mod camera;
+mod compute;
+mod instance;
+mod model;
+mod render_target;
+mod texture;
+
+use camera::{Camera, CameraController, CameraUniform};
+use cgmath::{InnerSpace, Rotation3, Zero};
+use compute::ComputePipeline;
+use instance::{Instance, InstanceRaw};
+use model::{DrawModel, Model, ModelVertex, Vertex};
+use render_target::TextureTarget;
+use texture::Texture;
+
+/// Where a call to `State::render` should draw its frame.
+#[derive(Clone, Copy)]
+enum RenderTarget<'a> {
+    SwapChain,
+    Texture(&'a TextureTarget),
 }
 
-impl Vertex {
-    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::InputStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 0,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x2,
-                },
-            ]
-        }
-    }
-}
-
-const VERTICES: &[Vertex] = &[
-    // Changed
-    Vertex { position: [-0.0868241, 0.49240386, 0.0], tex_coords: [0.4131759, 0.00759614], }, // A
-    Vertex { position: [-0.49513406, 0.06958647, 0.0], tex_coords: [0.0048659444, 0.43041354], }, // B
-    Vertex { position: [-0.21918549, -0.44939706, 0.0], tex_coords: [0.28081453, 0.949397057], }, // C
-    Vertex { position: [0.35966998, -0.3473291, 0.0], tex_coords: [0.85967, 0.84732911], }, // D
-    Vertex { position: [0.44147372, 0.2347359, 0.0], tex_coords: [0.9414737, 0.2652641], }, // E
-];
-
-
-const INDICES: &[u16] = &[
-    0, 1, 4,
-    1, 2, 4,
-    2, 3, 4,
-    // WGPU requires 4 bytes buffer alignment (packing)
-    // Above there are 9 u16 numbers which is 9 x 2 bytes
-    // We add one more u16 to square this
-    /* padding */ 0,
-];
-
-const SECOND_INDICES: &[u16] = &[
-    0, 1, 4,
-    2, 3, 4,
-    // WGPU requires 4 bytes buffer alignment (packing)
-    // Above there are 9 u16 numbers which is 9 x 2 bytes
-    // We add one more u16 to square this
-    /* padding */ 0,
-];
-
-bitflags! {
-    struct Levers: u32 {
-        const LEVER1 = 0b00000001;
-        const LEVER2 = 0b00000010;
-    }
+const NUM_INSTANCES_PER_ROW: u32 = 10;
+const INSTANCE_DISPLACEMENT: cgmath::Vector3<f32> = cgmath::Vector3::new(
+    NUM_INSTANCES_PER_ROW as f32 * 0.5,
+    0.0,
+    NUM_INSTANCES_PER_ROW as f32 * 0.5,
+);
+
+fn create_multisampled_framebuffer(
+    device: &wgpu::Device,
+    sc_desc: &wgpu::SwapChainDescriptor,
+    sample_count: u32,
+) -> wgpu::TextureView {
+    let multisampled_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Multisampled Framebuffer"),
+        size: wgpu::Extent3d {
+            width: sc_desc.width,
+            height: sc_desc.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format: sc_desc.format,
+        usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
+    });
+
+    multisampled_texture.create_view(&wgpu::TextureViewDescriptor::default())
 }
 
-
 struct State {
     surface: wgpu::Surface,
     device: wgpu::Device,
@@ -84,13 +67,19 @@ struct State {
     size: winit::dpi::PhysicalSize<u32>,
     mouse_pos: cgmath::Point2<f64>,
     render_pipeline: wgpu::RenderPipeline,
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-    num_indices: u32,
-    second_index_buffer: wgpu::Buffer,
-    second_num_indices: u32,
-    levers: Levers,
-    diffuse_bind_group: wgpu::BindGroup,
+    obj_model: Model,
+    camera: Camera,
+    camera_controller: CameraController,
+    camera_uniform: CameraUniform,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    depth_texture: Texture,
+    sample_count: u32,
+    multisampled_framebuffer: Option<wgpu::TextureView>,
+    instances: Vec<Instance>,
+    instance_buffer: wgpu::Buffer,
+    compute_pipeline: ComputePipeline,
+    compute_bind_group: wgpu::BindGroup,
 }
 
 impl State {
@@ -141,67 +130,18 @@ impl State {
         };
         let swap_chain = device.create_swap_chain(&surface, &sc_desc);
 
-        let diffuse_bytes = include_bytes!("tree.png");
-        let diffuse_image = image::load_from_memory(diffuse_bytes)?;
-        let diffuse_rgba = diffuse_image.as_rgba8().expect("Can't transform image info");
+        // 4x MSAA is the level most wgpu backends support without falling back to
+        // software resolve, so it's a safe default.
+        let sample_count = 4;
 
-        use image::GenericImageView;
-        let dimensions = diffuse_image.dimensions();
+        let depth_texture = Texture::create_depth_texture(&device, &sc_desc, sample_count, "depth_texture");
 
-        let texture_size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            // All textures are stored as 3D, 2D textures have depth of 1.
-            depth_or_array_layers: 1,
+        let multisampled_framebuffer = if sample_count > 1 {
+            Some(create_multisampled_framebuffer(&device, &sc_desc, sample_count))
+        } else {
+            None
         };
 
-        let diffuse_texture = device.create_texture(
-            &wgpu::TextureDescriptor {
-                // All textures are stored as 3D, 2D textures have depth of 1.
-                size: texture_size,
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
-                // SAMPLED tells WGPU to use the texture in shaders
-                // COPY_DST tells WGPU that we want to copy data to this texture
-                usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
-                label: Some("diffuse_texture"),
-            }
-        );
-
-        queue.write_texture(
-            // Where to copy the pixel data
-            wgpu::ImageCopyTexture {
-                texture: &&diffuse_texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            // The pixel data
-            diffuse_rgba,
-            // Layout of the texture
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: std::num::NonZeroU32::new(4 * dimensions.0),
-                rows_per_image: std::num::NonZeroU32::new(dimensions.1),
-            },
-            texture_size
-        );
-
-        let diffuse_texture_view = diffuse_texture.create_view(
-            &wgpu::TextureViewDescriptor::default()
-        );
-
-        let diffuse_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
-
         let texture_bind_group_layout = device.create_bind_group_layout(
             &wgpu::BindGroupLayoutDescriptor {
                 entries: &[
@@ -229,20 +169,58 @@ impl State {
             }
         );
 
-        let diffuse_bind_group = device.create_bind_group(
+        let obj_model = Model::load(&device, &queue, &texture_bind_group_layout, "res/cube.obj")?;
+
+        let camera = Camera {
+            eye: cgmath::Point3::new(0.0, 1.0, 2.0),
+            target: cgmath::Point3::new(0.0, 0.0, 0.0),
+            up: cgmath::Vector3::unit_y(),
+            aspect: sc_desc.width as f32 / sc_desc.height as f32,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+        };
+        let camera_controller = CameraController::new(0.2);
+
+        let mut camera_uniform = CameraUniform::new();
+        camera_uniform.update_view_proj(&camera);
+
+        let camera_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Camera Buffer"),
+                contents: bytemuck::cast_slice(&[camera_uniform]),
+                usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            }
+        );
+
+        let camera_bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+                label: Some("camera_bind_group_layout"),
+            }
+        );
+
+        let camera_bind_group = device.create_bind_group(
             &wgpu::BindGroupDescriptor {
-                label: Some("diffuse_bind_group"),
-                layout: &&texture_bind_group_layout,
+                layout: &camera_bind_group_layout,
                 entries: &[
                     wgpu::BindGroupEntry {
                         binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&diffuse_texture_view),
+                        resource: camera_buffer.as_entire_binding(),
                     },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&diffuse_sampler),
-                    }
                 ],
+                label: Some("camera_bind_group"),
             }
         );
 
@@ -254,7 +232,7 @@ impl State {
 
         let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Render Pipeline Layout"),
-            bind_group_layouts: &[&texture_bind_group_layout],
+            bind_group_layouts: &[&texture_bind_group_layout, &camera_bind_group_layout],
             push_constant_ranges: &[],
         });
 
@@ -264,7 +242,7 @@ impl State {
             vertex: wgpu::VertexState {
                 module: &shader,
                 entry_point: "main",
-                buffers: &[Vertex::desc()],
+                buffers: &[ModelVertex::desc(), InstanceRaw::desc()],
             },
             fragment: Some(wgpu::FragmentState {
                 module: &shader,
@@ -287,43 +265,98 @@ impl State {
                 // Enabling this requires Features::CONSERVATIVE_RASTERIZATION to be enabled.
                 conservative: false,
             },
-            depth_stencil: None,
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
             multisample: wgpu::MultisampleState {
-                count: 1,
+                count: sample_count,
                 mask: !0,
                 alpha_to_coverage_enabled: false,
             },
         });
 
-        let vertex_buffer = device.create_buffer_init(
+        let instances = (0..NUM_INSTANCES_PER_ROW)
+            .flat_map(|z| {
+                (0..NUM_INSTANCES_PER_ROW).map(move |x| {
+                    let position = cgmath::Vector3 {
+                        x: x as f32,
+                        y: 0.0,
+                        z: z as f32,
+                    } - INSTANCE_DISPLACEMENT;
+
+                    let rotation = if position.is_zero() {
+                        // This is needed so an object at (0, 0, 0) won't be scaled
+                        // to zero, as Quaternions can affect scale if they're not
+                        // created correctly.
+                        cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0))
+                    } else {
+                        cgmath::Quaternion::from_axis_angle(position.normalize(), cgmath::Deg(45.0))
+                    };
+
+                    Instance { position, rotation }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
+        // STORAGE so the compute pass below can mutate it in place, VERTEX so the
+        // render pass can keep reading it straight back out as instance data.
+        let instance_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(VERTICES),
-                usage: wgpu::BufferUsage::VERTEX,
+                label: Some("Instance Buffer"),
+                contents: bytemuck::cast_slice(&instance_data),
+                usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::VERTEX,
             }
         );
 
-        let index_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Index Buffer"),
-                contents: bytemuck::cast_slice(INDICES),
-                usage: wgpu::BufferUsage::INDEX,
+        let compute_bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+                label: Some("compute_bind_group_layout"),
             }
         );
 
-        let num_indices = INDICES.len() as u32;
-
-        let second_index_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Second Index Buffer"),
-                contents: bytemuck::cast_slice(SECOND_INDICES),
-                usage: wgpu::BufferUsage::INDEX,
+        let compute_bind_group = device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                layout: &compute_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: instance_buffer.as_entire_binding(),
+                    },
+                ],
+                label: Some("compute_bind_group"),
             }
         );
 
-        let second_num_indices = SECOND_INDICES.len() as u32;
+        let compute_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Compute Shader"),
+            flags: wgpu::ShaderFlags::all(),
+            source: wgpu::ShaderSource::Wgsl(include_str!("compute.wgsl").into()),
+        });
 
-        let levers = Levers::empty();
+        let compute_pipeline = ComputePipeline::new(
+            &device,
+            &[&compute_bind_group_layout],
+            &compute_shader,
+            "main",
+            "Instance Update Pipeline",
+        );
 
         Ok(
             Self {
@@ -335,13 +368,19 @@ impl State {
                 size,
                 mouse_pos: cgmath::Point2 {x: 0.0, y: 0.0},
                 render_pipeline,
-                vertex_buffer,
-                index_buffer,
-                second_index_buffer,
-                num_indices,
-                second_num_indices,
-                levers,
-                diffuse_bind_group,
+                obj_model,
+                camera,
+                camera_controller,
+                camera_uniform,
+                camera_buffer,
+                camera_bind_group,
+                depth_texture,
+                sample_count,
+                multisampled_framebuffer,
+                instances,
+                instance_buffer,
+                compute_pipeline,
+                compute_bind_group,
             }
         )
     }
@@ -351,9 +390,22 @@ impl State {
         self.sc_desc.width = new_size.width;
         self.sc_desc.height = new_size.height;
         self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
+        self.camera.aspect = self.sc_desc.width as f32 / self.sc_desc.height as f32;
+        self.depth_texture = Texture::create_depth_texture(
+            &self.device, &self.sc_desc, self.sample_count, "depth_texture",
+        );
+        self.multisampled_framebuffer = if self.sample_count > 1 {
+            Some(create_multisampled_framebuffer(&self.device, &self.sc_desc, self.sample_count))
+        } else {
+            None
+        };
     }
 
     fn input(&mut self, event: &WindowEvent) -> bool {
+        if self.camera_controller.process_events(event) {
+            return true;
+        }
+
         match event {
             WindowEvent::CursorMoved {position, ..} => {
                 self.mouse_pos.x = position.x;
@@ -363,18 +415,12 @@ impl State {
             },
             WindowEvent::KeyboardInput { input, .. } => match input {
                 KeyboardInput {
-                    state,
-                    virtual_keycode: Some(VirtualKeyCode::Space),
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::P),
                     ..
-                } => match state {
-                    ElementState::Pressed => {
-                        self.levers = self.levers | Levers::LEVER1;
-                        true
-                    },
-                    ElementState::Released => {
-                        self.levers = self.levers & !Levers::LEVER1;
-                        true
-                    },
+                } => {
+                    self.capture_screenshot();
+                    true
                 },
                 _ => false
             },
@@ -382,13 +428,35 @@ impl State {
         }
     }
 
+    fn capture_screenshot(&mut self) {
+        let target = TextureTarget::new(&self.device, self.size.width, self.size.height, self.sc_desc.format);
+        match self.render(RenderTarget::Texture(&target)) {
+            Ok(_) => info!("Saved screenshot to screenshot.png"),
+            Err(e) => error!("Failed to capture screenshot: {:?}", e),
+        }
+    }
+
     fn update(&mut self) {
+        self.camera_controller.update_camera(&mut self.camera);
+        self.camera_uniform.update_view_proj(&self.camera);
+        self.queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[self.camera_uniform]),
+        );
     }
 
-    fn render(&mut self) -> Result<(), wgpu::SwapChainError> {
-        let frame = self.swap_chain
-            .get_current_frame()?
-            .output;
+    fn render(&mut self, target: RenderTarget) -> Result<(), wgpu::SwapChainError> {
+        let frame = match target {
+            RenderTarget::SwapChain => Some(self.swap_chain.get_current_frame()?.output),
+            RenderTarget::Texture(_) => None,
+        };
+
+        let resolve_view = match (&target, &frame) {
+            (RenderTarget::SwapChain, Some(frame)) => &frame.view,
+            (RenderTarget::Texture(texture_target), _) => &texture_target.view,
+            (RenderTarget::SwapChain, None) => unreachable!(),
+        };
 
         let mut encoder = self.device.create_command_encoder(
             &wgpu::CommandEncoderDescriptor {
@@ -396,6 +464,21 @@ impl State {
             }
         );
 
+        let (color_view, resolve_target) = match &self.multisampled_framebuffer {
+            Some(msaa_view) => (msaa_view, Some(resolve_view)),
+            None => (resolve_view, None),
+        };
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Instance Update Pass"),
+            });
+            compute_pass.set_pipeline(&self.compute_pipeline.pipeline);
+            compute_pass.set_bind_group(0, &self.compute_bind_group, &[]);
+            let workgroups = (self.instances.len() as u32).div_ceil(64);
+            compute_pass.dispatch(workgroups, 1, 1);
+        }
+
         {
             let mut render_pass = encoder.begin_render_pass(
                 &wgpu::RenderPassDescriptor {
@@ -403,8 +486,8 @@ impl State {
                     color_attachments: &[
                         // This is what [[location(0)]] in the fragment shader targets
                         wgpu::RenderPassColorAttachment {
-                            view: &frame.view,
-                            resolve_target: None,
+                            view: color_view,
+                            resolve_target,
                             ops: wgpu::Operations {
                                 load: wgpu::LoadOp::Clear(wgpu::Color {
                                     r: 0.1,
@@ -416,31 +499,39 @@ impl State {
                             }
                         }
                     ],
-                    depth_stencil_attachment: None,
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: &self.depth_texture.view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: true,
+                        }),
+                        stencil_ops: None,
+                    }),
                 }
             );
 
-            let data = {
-                if self.levers.contains(Levers::LEVER1) {
-                    (&self.second_index_buffer, self.second_num_indices)
-                } else {
-                    (&self.index_buffer, self.num_indices)
-                }
-            };
-
             render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(data.0.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(
-                0..data.1,
-                0,
-                0..1
+            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+            render_pass.draw_model_instanced(
+                &self.obj_model,
+                0..self.instances.len() as u32,
+                &self.camera_bind_group,
             );
         }
 
+        if let RenderTarget::Texture(texture_target) = target {
+            texture_target.copy_to_buffer(&mut encoder);
+        }
+
         self.queue.submit(std::iter::once(encoder.finish()));
 
+        if let RenderTarget::Texture(texture_target) = target {
+            self.device.poll(wgpu::Maintain::Wait);
+            if let Err(e) = texture_target.save_png(&self.device, "screenshot.png") {
+                error!("Failed to write screenshot.png: {:?}", e);
+            }
+        }
+
         Ok(())
     }
 }
@@ -489,7 +580,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
             Event::RedrawRequested(_) => {
                 state.update();
-                match state.render() {
+                match state.render(RenderTarget::SwapChain) {
                     Ok(_) => {},
                     // Recreate the swap chain if lost
                     Err(wgpu::SwapChainError::Lost) => state.resize(state.size),
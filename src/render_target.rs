@@ -0,0 +1,113 @@
+use std::path::Path;
+
+/// An offscreen render target that can be read back to the CPU and written out as a PNG.
+pub struct TextureTarget {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    output_buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    unpadded_bytes_per_row: u32,
+    padded_bytes_per_row: u32,
+    format: wgpu::TextureFormat,
+}
+
+impl TextureTarget {
+    /// `format` must match the swap chain's format (`sc_desc.format`), since this
+    /// texture is drawn into by the same pipeline/MSAA resolve that targets the
+    /// swap chain and wgpu validates that resolve source/target formats match.
+    pub fn new(device: &wgpu::Device, width: u32, height: u32, format: wgpu::TextureFormat) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Screenshot Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        // wgpu requires bytes_per_row to be a multiple of COPY_BYTES_PER_ROW_ALIGNMENT (256),
+        // so the buffer's row stride is padded up and cropped back off when we read it.
+        let unpadded_bytes_per_row = width * 4;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padding = (align - unpadded_bytes_per_row % align) % align;
+        let padded_bytes_per_row = unpadded_bytes_per_row + padding;
+
+        let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Screenshot Output Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            texture,
+            view,
+            output_buffer,
+            width,
+            height,
+            unpadded_bytes_per_row,
+            padded_bytes_per_row,
+            format,
+        }
+    }
+
+    pub fn copy_to_buffer(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.output_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(self.padded_bytes_per_row),
+                    rows_per_image: std::num::NonZeroU32::new(self.height),
+                },
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    pub fn save_png(&self, device: &wgpu::Device, path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+        let buffer_slice = self.output_buffer.slice(..);
+        let mapping = buffer_slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        futures::executor::block_on(mapping)?;
+
+        let padded_data = buffer_slice.get_mapped_range();
+        let mut pixels = Vec::with_capacity((self.unpadded_bytes_per_row * self.height) as usize);
+        for row in padded_data.chunks(self.padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..self.unpadded_bytes_per_row as usize]);
+        }
+        drop(padded_data);
+        self.output_buffer.unmap();
+
+        // Most desktop backends prefer a Bgra8* swap chain format; the `image`
+        // crate only writes RGBA, so swap the red/blue channels back in that case.
+        if matches!(
+            self.format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            for pixel in pixels.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        image::save_buffer(path, &pixels, self.width, self.height, image::ColorType::Rgba8)?;
+
+        Ok(())
+    }
+}